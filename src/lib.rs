@@ -16,6 +16,13 @@ pub enum OrderType {
     IOC,
     FOK,
     MakerOnly,
+    /// Rests at `oracle_price + peg_offset` (clamped and tick-rounded) instead of a fixed price.
+    OraclePegged,
+    /// Good-till-time: resting order invalid once `expire_ts <= now_ts`.
+    GoodTillTime,
+    /// Like `PostOnly`, but instead of being rejected when it would cross, reprices to just
+    /// inside the opposing best quote and rests there.
+    PostOnlySlide,
 }
 
 #[derive(Debug, Clone)]
@@ -27,8 +34,19 @@ pub struct Order {
     pub qty: u64,
     pub remaining: u64,
     pub otype: OrderType,
+    /// Only meaningful when `otype == OrderType::OraclePegged`; signed offset from the oracle price.
+    pub peg_offset: i64,
+    /// Only meaningful when `otype == OrderType::GoodTillTime`; the order is invalid once `now_ts >= expire_ts`.
+    pub expire_ts: Option<u64>,
+    /// Only meaningful when `otype == OrderType::Market`; bounds how far the fill price may walk
+    /// away from the best opposing touch before the order stops matching.
+    pub max_slippage_bps: Option<u64>,
 }
 
+/// Cap on how many expired resting orders `match_order` will cancel in a single `submit` call, so
+/// one taker can't be stuck paying an unbounded cleanup cost for stale liquidity.
+const DROP_EXPIRED_LIMIT: usize = 5;
+
 #[derive(Debug, Clone)]
 pub struct Fill {
     pub maker_id: u64,
@@ -37,11 +55,195 @@ pub struct Fill {
     pub qty: u64,
 }
 
+/// How to resolve a taker matching against its own resting order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelfTradePolicy {
+    /// Decrement both legs by `min(remaining)`, cancelling whichever (or both) reaches zero, with no `Fill` emitted.
+    DecrementAndCancel,
+    /// Cancel the resting maker order and keep matching the incoming order against the rest of the book.
+    CancelResting,
+    /// Cancel the remainder of the incoming (taking) order immediately, leaving the resting maker order untouched.
+    CancelTaking,
+    /// Cancel both the resting maker order and the remainder of the incoming order.
+    CancelBoth,
+}
+
+/// Result of a matching pass: the fills produced, any order ids cancelled to avoid a wash trade,
+/// and (if the order ended up resting) the price it actually rests at — which may differ from the
+/// submitted price for a repriced `PostOnlySlide` order.
 #[derive(Debug, Clone)]
+pub struct MatchReport {
+    pub fills: Vec<Fill>,
+    pub self_trade_cancellations: Vec<u64>,
+    pub resting_price: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum RiskError {
     PriceOutOfRange,
     PositionLimit,
     RateLimit,
+    /// An `OraclePegged` order was submitted (or needs repricing) before the symbol had an oracle price set.
+    OracleStale,
+    /// `price` is not a multiple of the market's configured `tick_size`.
+    InvalidTick,
+    /// `qty` is not a multiple of the market's configured `lot_size`.
+    InvalidLot,
+    /// `qty` is below the market's configured `min_size`.
+    BelowMinSize,
+    /// `configure_market` was given a degenerate `MarketConfig` (e.g. a zero `tick_size` or
+    /// `lot_size`, which would make every `submit` panic on the modulo check).
+    InvalidMarketConfig,
+    /// `modify` was given a `new_price` for an `OraclePegged` order. A pegged order's resting
+    /// price is always derived from `oracle_price + peg_offset`; there is no `peg_offset` input
+    /// to `modify`, so an absolute `new_price` would just be overwritten by the next
+    /// `set_oracle_price` and go stale in the meantime.
+    PegPriceNotModifiable,
+}
+
+/// Why an order left the book, reported via `Event::Out`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutReason {
+    Filled,
+    Cancelled,
+    Expired,
+}
+
+/// One occurrence on the book. Pushed onto a `MatchingEngine`'s `EventQueue` alongside (not instead
+/// of) the `MatchReport` returned to the caller, so a downstream settlement/ledger process can
+/// replay matching activity off the hot path.
+#[derive(Debug, Clone)]
+pub enum EventKind {
+    Fill {
+        maker_id: u64,
+        taker_id: u64,
+        price: u64,
+        qty: u64,
+        ts: u64,
+    },
+    Out {
+        order_id: u64,
+        reason: OutReason,
+    },
+    PlacedResting {
+        order_id: u64,
+    },
+}
+
+/// An `EventKind` tagged with a monotonically increasing sequence number, so a consumer can detect
+/// gaps and apply events idempotently.
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub seq: u64,
+    pub kind: EventKind,
+}
+
+/// Append-only log of matching events: matching only ever pushes, and a settlement process
+/// drains it independently and at its own pace.
+pub struct EventQueue {
+    events: VecDeque<Event>,
+    next_seq: u64,
+}
+
+impl EventQueue {
+    pub fn new() -> Self {
+        EventQueue {
+            events: VecDeque::new(),
+            next_seq: 0,
+        }
+    }
+
+    fn push(&mut self, kind: EventKind) {
+        self.events.push_back(Event {
+            seq: self.next_seq,
+            kind,
+        });
+        self.next_seq += 1;
+    }
+
+    /// Remove and return up to `max` events from the front of the queue, oldest first.
+    pub fn drain(&mut self, max: usize) -> Vec<Event> {
+        let n = max.min(self.events.len());
+        self.events.drain(..n).collect()
+    }
+}
+
+/// Parameters for a new order, passed to `submit`/`batch_submit`. Grouping these avoids each new
+/// order attribute (`peg_offset`, `expire_ts`, `max_slippage_bps`, ...) becoming its own
+/// positional argument, which made `submit` unwieldy and let two `Option<u64>` args be transposed
+/// at a call site with no type-level protection.
+#[derive(Debug, Clone, Copy)]
+pub struct OrderRequest {
+    pub id: u64,
+    pub user_id: u64,
+    pub otype: OrderType,
+    pub side: Side,
+    pub price: u64,
+    pub qty: u64,
+    /// Only meaningful when `otype == OrderType::OraclePegged`; signed offset from the oracle price.
+    pub peg_offset: i64,
+    /// Only meaningful when `otype == OrderType::GoodTillTime`; the order is invalid once `now_ts >= expire_ts`.
+    pub expire_ts: Option<u64>,
+    /// Only meaningful when `otype == OrderType::Market`; bounds how far the fill price may walk
+    /// away from the best opposing touch before the order stops matching.
+    pub max_slippage_bps: Option<u64>,
+}
+
+impl Default for OrderRequest {
+    /// A plain fixed-price order with no peg offset, expiry, or slippage bound. `id`, `user_id`,
+    /// `otype`, `side`, `price`, and `qty` have no sensible default and must always be set
+    /// explicitly, e.g. `OrderRequest { id, user_id, otype, side, price, qty, ..Default::default() }`.
+    fn default() -> Self {
+        OrderRequest {
+            id: 0,
+            user_id: 0,
+            otype: OrderType::Limit,
+            side: Side::Bid,
+            price: 0,
+            qty: 0,
+            peg_offset: 0,
+            expire_ts: None,
+            max_slippage_bps: None,
+        }
+    }
+}
+
+/// Per-symbol price/quantity granularity.
+#[derive(Debug, Clone, Copy)]
+pub struct MarketConfig {
+    pub tick_size: u64,
+    pub lot_size: u64,
+    pub min_size: u64,
+    pub price_floor: u64,
+    pub price_ceil: u64,
+}
+
+impl Default for MarketConfig {
+    /// Matches the engine's original global `PRECISION`-based behavior.
+    fn default() -> Self {
+        MarketConfig {
+            tick_size: PRECISION,
+            lot_size: PRECISION,
+            min_size: 0,
+            price_floor: MIN_PRICE,
+            price_ceil: MAX_PRICE,
+        }
+    }
+}
+
+/// Clamp `oracle_price + offset` into `[floor, ceil]` and round down to the nearest tick.
+///
+/// `offset` comes straight from a caller-supplied `peg_offset`, so the addition saturates
+/// instead of panicking on overflow; a saturated result still clamps into `[floor, ceil]`.
+fn effective_peg_price(oracle_price: u64, offset: i64, tick: u64, floor: u64, ceil: u64) -> u64 {
+    let raw = (oracle_price as i64).saturating_add(offset).clamp(floor as i64, ceil as i64) as u64;
+    (raw / tick) * tick
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LevelSrc {
+    Fixed(u64),
+    Pegged(i64),
 }
 
 pub const PRECISION: u64 = 100_000_000;
@@ -51,6 +253,7 @@ pub const MAX_PRICE: u64 = 1_000_000 * PRECISION;
 pub struct RiskEngine {
     position_limits: HashMap<u64, u64>,
     rate_limits: HashMap<u64, u64>,
+    self_trade_policies: HashMap<u64, SelfTradePolicy>,
 }
 
 impl RiskEngine {
@@ -58,6 +261,7 @@ impl RiskEngine {
         RiskEngine {
             position_limits: HashMap::new(),
             rate_limits: HashMap::new(),
+            self_trade_policies: HashMap::new(),
         }
     }
 
@@ -83,13 +287,37 @@ impl RiskEngine {
     pub fn set_position_limit(&mut self, user_id: u64, max_qty: u64) {
         self.position_limits.insert(user_id, max_qty);
     }
+
+    pub fn set_self_trade_policy(&mut self, user_id: u64, policy: SelfTradePolicy) {
+        self.self_trade_policies.insert(user_id, policy);
+    }
+
+    /// Returns `user_id`'s configured self-trade policy, defaulting to `CancelResting` for any
+    /// user who never calls `set_self_trade_policy`. This is a deliberate behavior change from
+    /// this engine's original silent skip-and-continue on a self-cross: an unconfigured user's
+    /// resting order can now be cancelled by their own later taker order. `CancelResting` was
+    /// chosen as the default because it mirrors the old behavior most closely for the taker side
+    /// (the incoming order keeps matching against the rest of the book instead of being cancelled
+    /// itself); callers that want a different outcome choose one per-user via
+    /// `set_self_trade_policy`. None of the policies reproduces the old skip-and-continue.
+    pub fn self_trade_policy(&self, user_id: u64) -> SelfTradePolicy {
+        self.self_trade_policies
+            .get(&user_id)
+            .copied()
+            .unwrap_or(SelfTradePolicy::CancelResting)
+    }
 }
 
 pub struct OrderBook {
     bids: BTreeMap<u64, VecDeque<usize>>,
     asks: BTreeMap<u64, VecDeque<usize>>,
+    // Oracle-pegged resting orders, keyed by their signed offset rather than a fixed price.
+    bid_pegged: BTreeMap<i64, VecDeque<usize>>,
+    ask_pegged: BTreeMap<i64, VecDeque<usize>>,
     orders: Slab<Order>,
     by_id: HashMap<u64, usize>,
+    oracle_price: Option<u64>,
+    market_config: MarketConfig,
 }
 
 impl OrderBook {
@@ -97,24 +325,194 @@ impl OrderBook {
         OrderBook {
             bids: BTreeMap::new(),
             asks: BTreeMap::new(),
+            bid_pegged: BTreeMap::new(),
+            ask_pegged: BTreeMap::new(),
             orders: Slab::new(),
             by_id: HashMap::new(),
+            market_config: MarketConfig::default(),
+            oracle_price: None,
         }
     }
 
-    pub fn submit(
+    /// Configure this market's tick size, lot size, and minimum order size. Rejects a degenerate
+    /// `tick_size`/`lot_size` of zero, which would otherwise panic the first time `submit` takes
+    /// the modulo of a price or qty against it.
+    pub fn configure_market(&mut self, config: MarketConfig) -> Result<(), RiskError> {
+        if config.tick_size == 0 || config.lot_size == 0 {
+            return Err(RiskError::InvalidMarketConfig);
+        }
+        self.market_config = config;
+        Ok(())
+    }
+
+    fn peg_price(&self, oracle: u64, offset: i64) -> u64 {
+        effective_peg_price(
+            oracle,
+            offset,
+            self.market_config.tick_size,
+            self.market_config.price_floor,
+            self.market_config.price_ceil,
+        )
+    }
+
+    /// Update the oracle price used to reprice resting `OraclePegged` orders, re-deriving
+    /// matchability for any that now cross the book. `now_ts` is used to skip already-expired
+    /// resting makers during matching, without reaping them (see `try_cross_one_pegged`), and
+    /// as the `ts` of any `Fill` events this produces.
+    pub fn set_oracle_price(
         &mut self,
-        id: u64,
-        user_id: u64,
-        otype: OrderType,
-        side: Side,
         price: u64,
-        qty: u64,
+        now_ts: u64,
+        risk: &RiskEngine,
+        events: &mut EventQueue,
+    ) -> MatchReport {
+        self.oracle_price = Some(price);
+        let mut report = MatchReport {
+            fills: Vec::new(),
+            self_trade_cancellations: Vec::new(),
+            resting_price: None,
+        };
+        self.reprice_pegged(now_ts, &mut report, risk, events);
+        report
+    }
+
+    /// After the oracle moves, resting pegged orders may now cross the opposite side. Pop and
+    /// re-run the best-priced pegged order on each side through the matcher until nothing crosses.
+    fn reprice_pegged(
+        &mut self,
+        now_ts: u64,
+        report: &mut MatchReport,
+        risk: &RiskEngine,
+        events: &mut EventQueue,
+    ) {
+        loop {
+            let mut crossed = false;
+            if self.try_cross_one_pegged(Side::Bid, now_ts, report, risk, events) {
+                crossed = true;
+            }
+            if self.try_cross_one_pegged(Side::Ask, now_ts, report, risk, events) {
+                crossed = true;
+            }
+            if !crossed {
+                break;
+            }
+        }
+    }
+
+    fn try_cross_one_pegged(
+        &mut self,
+        side: Side,
+        now_ts: u64,
+        report: &mut MatchReport,
+        risk: &RiskEngine,
+        events: &mut EventQueue,
+    ) -> bool {
+        let Some(oracle) = self.oracle_price else {
+            return false;
+        };
+        let pegged = match side {
+            Side::Bid => &self.bid_pegged,
+            Side::Ask => &self.ask_pegged,
+        };
+        let best_offset = match side {
+            Side::Bid => pegged.keys().next_back().copied(),
+            Side::Ask => pegged.keys().next().copied(),
+        };
+        let Some(offset) = best_offset else {
+            return false;
+        };
+        let eff_price = self.peg_price(oracle, offset);
+        let would_cross = match side {
+            Side::Bid => self
+                .best_opposing_price(Side::Bid, now_ts)
+                .map_or(false, |ask| eff_price >= ask),
+            Side::Ask => self
+                .best_opposing_price(Side::Ask, now_ts)
+                .map_or(false, |bid| eff_price <= bid),
+        };
+        if !would_cross {
+            return false;
+        }
+
+        let queue = match side {
+            Side::Bid => self.bid_pegged.get_mut(&offset),
+            Side::Ask => self.ask_pegged.get_mut(&offset),
+        };
+        let Some(idx) = queue.and_then(|q| q.pop_front()) else {
+            return false;
+        };
+        let empty_after_pop = match side {
+            Side::Bid => self.bid_pegged.get(&offset).map_or(true, |q| q.is_empty()),
+            Side::Ask => self.ask_pegged.get(&offset).map_or(true, |q| q.is_empty()),
+        };
+        if empty_after_pop {
+            match side {
+                Side::Bid => self.bid_pegged.remove(&offset),
+                Side::Ask => self.ask_pegged.remove(&offset),
+            };
+        }
+
+        let mut order = self.orders[idx].clone();
+        self.remove_order(order.id);
+        order.price = eff_price;
+        let policy = risk.self_trade_policy(order.user_id);
+        let mut expired_cancellations = Vec::new();
+        // Repricing still needs the real `now_ts` so `match_order` skips makers that are already
+        // expired by wall-clock time; it just doesn't reap them (budget 0), since that cleanup is
+        // handled by `submit` and `reap_expired`, not by an oracle tick.
+        let mut expired_budget = 0;
+        self.match_order(
+            &mut order,
+            &mut report.fills,
+            &mut report.self_trade_cancellations,
+            &mut expired_cancellations,
+            false,
+            policy,
+            now_ts,
+            &mut expired_budget,
+            events,
+        );
+        if order.remaining > 0 {
+            self.add_pegged_to_book(&order, events);
+        }
+        true
+    }
+
+    pub fn submit(
+        &mut self,
+        req: OrderRequest,
+        now_ts: u64,
         risk: &mut RiskEngine,
-    ) -> Result<Vec<Fill>, RiskError> {
-        if price % PRECISION != 0 || qty % PRECISION != 0 || price < MIN_PRICE || price > MAX_PRICE
-        {
+        events: &mut EventQueue,
+    ) -> Result<MatchReport, RiskError> {
+        let OrderRequest {
+            id,
+            user_id,
+            otype,
+            side,
+            price,
+            qty,
+            peg_offset,
+            expire_ts,
+            max_slippage_bps,
+        } = req;
+        if matches!(otype, OrderType::OraclePegged) {
+            if self.oracle_price.is_none() {
+                return Err(RiskError::OracleStale);
+            }
+        } else if matches!(otype, OrderType::Market) {
+            // `price` is a don't-care placeholder here; a Market order is bounded instead by
+            // `worst_acceptable_price` (when `max_slippage_bps` is set) or matches unbounded.
+        } else if price < self.market_config.price_floor || price > self.market_config.price_ceil {
             return Err(RiskError::PriceOutOfRange);
+        } else if price % self.market_config.tick_size != 0 {
+            return Err(RiskError::InvalidTick);
+        }
+        if qty % self.market_config.lot_size != 0 {
+            return Err(RiskError::InvalidLot);
+        }
+        if qty < self.market_config.min_size {
+            return Err(RiskError::BelowMinSize);
         }
         if risk.check_position_limit(user_id, qty) {
             return Err(RiskError::PositionLimit);
@@ -124,188 +522,470 @@ impl OrderBook {
         }
 
         let mut fills = Vec::new();
+        let mut self_trade_cancellations = Vec::new();
+        let mut expired_cancellations = Vec::new();
+        let mut expired_budget = DROP_EXPIRED_LIMIT;
+        let policy = risk.self_trade_policy(user_id);
+        let effective_price = if matches!(otype, OrderType::OraclePegged) {
+            self.peg_price(self.oracle_price.unwrap(), peg_offset)
+        } else {
+            price
+        };
         let mut order = Order {
             id,
             user_id,
             side,
-            price,
+            price: effective_price,
             qty,
             remaining: qty,
             otype,
+            peg_offset,
+            // Expiry applies only to `GoodTillTime` orders; any other type ignores `expire_ts`.
+            expire_ts: if matches!(otype, OrderType::GoodTillTime) {
+                expire_ts
+            } else {
+                None
+            },
+            max_slippage_bps,
         };
+        let mut resting_price = None;
 
         match otype {
             OrderType::Market => {
-                self.match_order(&mut order, &mut fills, true);
+                // With no slippage bound, fall back to the original unbounded behavior. Otherwise
+                // derive a worst-acceptable price from the current far touch and match against
+                // that instead of letting the order walk the book arbitrarily far.
+                match max_slippage_bps.and_then(|bps| self.worst_acceptable_price(side, bps, now_ts)) {
+                    Some(limit_price) => {
+                        order.price = limit_price;
+                        self.match_order(
+                            &mut order,
+                            &mut fills,
+                            &mut self_trade_cancellations,
+                            &mut expired_cancellations,
+                            false,
+                            policy,
+                            now_ts,
+                            &mut expired_budget,
+                            events,
+                        );
+                    }
+                    None => {
+                        self.match_order(
+                            &mut order,
+                            &mut fills,
+                            &mut self_trade_cancellations,
+                            &mut expired_cancellations,
+                            true,
+                            policy,
+                            now_ts,
+                            &mut expired_budget,
+                            events,
+                        );
+                    }
+                }
             }
             OrderType::Limit => {
-                self.match_order(&mut order, &mut fills, false);
+                self.match_order(
+                    &mut order,
+                    &mut fills,
+                    &mut self_trade_cancellations,
+                    &mut expired_cancellations,
+                    false,
+                    policy,
+                    now_ts,
+                    &mut expired_budget,
+                    events,
+                );
+                if order.remaining > 0 {
+                    self.add_to_book(&order, events);
+                    resting_price = Some(order.price);
+                }
+            }
+            OrderType::OraclePegged => {
+                // Cross if marketable, rest on the pegged side otherwise, like a Limit order.
+                self.match_order(
+                    &mut order,
+                    &mut fills,
+                    &mut self_trade_cancellations,
+                    &mut expired_cancellations,
+                    false,
+                    policy,
+                    now_ts,
+                    &mut expired_budget,
+                    events,
+                );
                 if order.remaining > 0 {
-                    self.add_to_book(&order);
+                    self.add_pegged_to_book(&order, events);
+                    resting_price = Some(order.price);
+                }
+            }
+            OrderType::GoodTillTime => {
+                // Cross if marketable, rest otherwise, like a Limit order, but carries `expire_ts`.
+                self.match_order(
+                    &mut order,
+                    &mut fills,
+                    &mut self_trade_cancellations,
+                    &mut expired_cancellations,
+                    false,
+                    policy,
+                    now_ts,
+                    &mut expired_budget,
+                    events,
+                );
+                if order.remaining > 0 {
+                    self.add_to_book(&order, events);
+                    resting_price = Some(order.price);
                 }
             }
             OrderType::PostOnly | OrderType::MakerOnly => {
-                if self.would_match(&order) {
+                if self.would_match(&order, now_ts) {
                     // 不挂单
                 } else {
-                    self.add_to_book(&order);
+                    self.add_to_book(&order, events);
+                    resting_price = Some(order.price);
+                }
+            }
+            OrderType::PostOnlySlide => {
+                // Instead of rejecting a crossing order, slide it to just inside the opposing
+                // best quote so it always rests passively. The slide target is clamped into
+                // [price_floor, price_ceil], which can land it back on the opposing touch (e.g.
+                // the touch sits at the floor/ceil already); re-check after clamping and reject,
+                // like a PostOnly rejection, rather than rest crossed.
+                if self.would_match(&order, now_ts) {
+                    if let Some(slid_price) = self.slide_price(&order, now_ts) {
+                        order.price = slid_price;
+                    }
+                }
+                if !self.would_match(&order, now_ts) {
+                    self.add_to_book(&order, events);
+                    resting_price = Some(order.price);
                 }
             }
             OrderType::IOC => {
-                self.match_order(&mut order, &mut fills, false);
+                self.match_order(
+                    &mut order,
+                    &mut fills,
+                    &mut self_trade_cancellations,
+                    &mut expired_cancellations,
+                    false,
+                    policy,
+                    now_ts,
+                    &mut expired_budget,
+                    events,
+                );
             }
             OrderType::FOK => {
-                if self.can_full_match(&order) {
-                    self.match_order(&mut order, &mut fills, false);
+                if self.can_full_match(&order, now_ts) {
+                    self.match_order(
+                        &mut order,
+                        &mut fills,
+                        &mut self_trade_cancellations,
+                        &mut expired_cancellations,
+                        false,
+                        policy,
+                        now_ts,
+                        &mut expired_budget,
+                        events,
+                    );
                 }
             }
         }
 
-        Ok(fills)
+        Ok(MatchReport {
+            fills,
+            self_trade_cancellations,
+            resting_price,
+        })
+    }
+
+    /// The worst price a `Market` order with `max_slippage_bps` should be willing to take,
+    /// derived from the current far touch (best opposing quote) rather than left unbounded.
+    /// Uses `best_opposing_price` rather than the raw first level so a level occupied only by
+    /// expired `GoodTillTime` makers doesn't anchor the slippage limit to phantom liquidity.
+    fn worst_acceptable_price(&self, side: Side, max_slippage_bps: u64, now_ts: u64) -> Option<u64> {
+        let touch = self.best_opposing_price(side, now_ts)?;
+        // `max_slippage_bps` comes straight from a caller-supplied `OrderRequest`, so saturate
+        // the same way `effective_peg_price` saturates `peg_offset`.
+        let slippage = touch.saturating_mul(max_slippage_bps) / 10_000;
+        Some(match side {
+            Side::Bid => touch.saturating_add(slippage),
+            Side::Ask => touch.saturating_sub(slippage),
+        })
+    }
+
+    /// The best opposing-side price (merging fixed and pegged levels) that still has resting,
+    /// non-expired quantity behind it, as of `now_ts`. A level occupied only by expired
+    /// `GoodTillTime` makers is phantom liquidity and must not count as marketable.
+    fn best_opposing_price(&self, incoming_side: Side, now_ts: u64) -> Option<u64> {
+        let (levels, book_side) = if matches!(incoming_side, Side::Bid) {
+            (self.ask_levels_in_order(), Side::Ask)
+        } else {
+            (self.bid_levels_in_order(), Side::Bid)
+        };
+        levels
+            .into_iter()
+            .find(|&(_, src)| self.resting_level_qty(book_side, src, now_ts) > 0)
+            .map(|(p, _)| p)
     }
 
-    fn would_match(&self, incoming: &Order) -> bool {
+    /// Where a `PostOnlySlide` order should rest instead of crossing: just inside the opposing
+    /// best quote, clamped into `[price_floor, price_ceil]` like every other resting price.
+    /// Returns `None` if that side of the book is empty (nothing to slide against).
+    fn slide_price(&self, order: &Order, now_ts: u64) -> Option<u64> {
+        let tick = self.market_config.tick_size;
+        let floor = self.market_config.price_floor;
+        let ceil = self.market_config.price_ceil;
+        match order.side {
+            Side::Bid => {
+                let best_ask = self.best_opposing_price(Side::Bid, now_ts)?;
+                Some(
+                    order
+                        .price
+                        .min(best_ask.saturating_sub(tick))
+                        .clamp(floor, ceil),
+                )
+            }
+            Side::Ask => {
+                let best_bid = self.best_opposing_price(Side::Ask, now_ts)?;
+                Some(order.price.max(best_bid + tick).clamp(floor, ceil))
+            }
+        }
+    }
+
+    /// Whether `incoming` would cross the book at all, checking both fixed-price and
+    /// oracle-pegged resting levels on the opposing side, excluding already-expired makers.
+    fn would_match(&self, incoming: &Order, now_ts: u64) -> bool {
         match incoming.side {
             Side::Bid => self
-                .asks
-                .keys()
-                .next()
-                .map_or(false, |&ask| incoming.price >= ask),
+                .best_opposing_price(Side::Bid, now_ts)
+                .map_or(false, |ask| incoming.price >= ask),
             Side::Ask => self
-                .bids
-                .keys()
-                .rev()
-                .next()
-                .map_or(false, |&bid| incoming.price <= bid),
+                .best_opposing_price(Side::Ask, now_ts)
+                .map_or(false, |bid| incoming.price <= bid),
         }
     }
 
-    fn can_full_match(&self, incoming: &Order) -> bool {
+    fn can_full_match(&self, incoming: &Order, now_ts: u64) -> bool {
         let mut remaining = incoming.remaining;
 
-        if matches!(incoming.side, Side::Bid) {
-            for (&price, queue) in self.asks.iter() {
+        let (levels, book_side) = if matches!(incoming.side, Side::Bid) {
+            (self.ask_levels_in_order(), Side::Ask)
+        } else {
+            (self.bid_levels_in_order(), Side::Bid)
+        };
+
+        for (price, src) in levels {
+            if matches!(incoming.side, Side::Bid) {
                 if incoming.price < price {
                     break;
                 }
-                let level_qty: u64 = queue.iter().map(|&idx| self.orders[idx].remaining).sum();
-                remaining = remaining.saturating_sub(level_qty);
-                if remaining == 0 {
-                    return true;
-                }
+            } else if incoming.price > price {
+                break;
             }
-        } else {
-            for (&price, queue) in self.bids.iter().rev() {
-                if incoming.price > price {
-                    break;
-                }
-                let level_qty: u64 = queue.iter().map(|&idx| self.orders[idx].remaining).sum();
-                remaining = remaining.saturating_sub(level_qty);
-                if remaining == 0 {
-                    return true;
-                }
+            let level_qty = self.resting_level_qty(book_side, src, now_ts);
+            remaining = remaining.saturating_sub(level_qty);
+            if remaining == 0 {
+                return true;
             }
         }
         false
     }
 
-    fn match_order(&mut self, incoming: &mut Order, fills: &mut Vec<Fill>, ignore_price: bool) {
+    /// Merge the fixed-price levels with the pegged levels (at their current effective price),
+    /// ordered ascending for asks / descending for bids so price priority holds across both.
+    /// Levels that land on the same price are not ordered by arrival time: fixed levels come
+    /// before pegged ones, and pegged ones keep their offset order.
+    fn ask_levels_in_order(&self) -> Vec<(u64, LevelSrc)> {
+        let mut levels: Vec<(u64, LevelSrc)> =
+            self.asks.keys().map(|&p| (p, LevelSrc::Fixed(p))).collect();
+        if let Some(oracle) = self.oracle_price {
+            levels.extend(
+                self.ask_pegged
+                    .keys()
+                    .map(|&off| (self.peg_price(oracle, off), LevelSrc::Pegged(off))),
+            );
+        }
+        levels.sort_by_key(|&(p, _)| p);
+        levels
+    }
+
+    fn bid_levels_in_order(&self) -> Vec<(u64, LevelSrc)> {
+        let mut levels: Vec<(u64, LevelSrc)> =
+            self.bids.keys().map(|&p| (p, LevelSrc::Fixed(p))).collect();
+        if let Some(oracle) = self.oracle_price {
+            levels.extend(
+                self.bid_pegged
+                    .keys()
+                    .map(|&off| (self.peg_price(oracle, off), LevelSrc::Pegged(off))),
+            );
+        }
+        levels.sort_by_key(|&(p, _)| std::cmp::Reverse(p));
+        levels
+    }
+
+    fn match_order(
+        &mut self,
+        incoming: &mut Order,
+        fills: &mut Vec<Fill>,
+        self_trade_cancellations: &mut Vec<u64>,
+        expired_cancellations: &mut Vec<u64>,
+        ignore_price: bool,
+        self_trade_policy: SelfTradePolicy,
+        now_ts: u64,
+        expired_budget: &mut usize,
+        events: &mut EventQueue,
+    ) {
         let mut to_remove_orders = Vec::new();
-        let mut prices_to_clean = Vec::new();
+        let mut levels_to_clean = Vec::new();
 
-        if matches!(incoming.side, Side::Bid) {
-            // Bid 吃 Ask (从小到大)
-            for (&book_price, queue) in self.asks.iter_mut() {
-                if incoming.remaining == 0 {
-                    break;
-                }
+        let levels = if matches!(incoming.side, Side::Bid) {
+            self.ask_levels_in_order()
+        } else {
+            self.bid_levels_in_order()
+        };
+
+        for (book_price, src) in levels {
+            if incoming.remaining == 0 {
+                break;
+            }
+            if matches!(incoming.side, Side::Bid) {
                 if !ignore_price && incoming.price < book_price {
                     break;
                 }
+            } else if !ignore_price && incoming.price > book_price {
+                break;
+            }
 
-                let mut indices_to_remove = Vec::new();
-
-                for (pos, &idx) in queue.iter().enumerate() {
-                    if incoming.remaining == 0 {
-                        break;
-                    }
-
-                    let maker = &mut self.orders[idx];
-                    if incoming.user_id == maker.user_id {
-                        continue;
-                    }
-
-                    let fill_qty = incoming.remaining.min(maker.remaining);
-                    incoming.remaining -= fill_qty;
-                    maker.remaining -= fill_qty;
-
-                    fills.push(Fill {
-                        maker_id: maker.id,
-                        taker_id: incoming.id,
-                        price: book_price,
-                        qty: fill_qty,
-                    });
-
-                    if maker.remaining == 0 {
-                        indices_to_remove.push(pos);
-                        to_remove_orders.push(maker.id);
-                    }
-                }
+            let queue = match (incoming.side, src) {
+                (Side::Bid, LevelSrc::Fixed(p)) => self.asks.get_mut(&p),
+                (Side::Bid, LevelSrc::Pegged(o)) => self.ask_pegged.get_mut(&o),
+                (Side::Ask, LevelSrc::Fixed(p)) => self.bids.get_mut(&p),
+                (Side::Ask, LevelSrc::Pegged(o)) => self.bid_pegged.get_mut(&o),
+            };
+            let Some(queue) = queue else { continue };
 
-                for &pos in indices_to_remove.iter().rev() {
-                    queue.remove(pos);
-                }
+            let mut indices_to_remove = Vec::new();
 
-                if queue.is_empty() {
-                    prices_to_clean.push(book_price);
-                }
-            }
-        } else {
-            // Ask 吃 Bid (从大到小)
-            for (&book_price, queue) in self.bids.iter_mut().rev() {
+            for (pos, &idx) in queue.iter().enumerate() {
                 if incoming.remaining == 0 {
                     break;
                 }
-                if !ignore_price && incoming.price > book_price {
-                    break;
-                }
-
-                let mut indices_to_remove = Vec::new();
-
-                for (pos, &idx) in queue.iter().enumerate() {
-                    if incoming.remaining == 0 {
-                        break;
-                    }
 
-                    let maker = &mut self.orders[idx];
-                    if incoming.user_id == maker.user_id {
+                let maker = &mut self.orders[idx];
+                if let Some(expire_ts) = maker.expire_ts {
+                    if expire_ts <= now_ts {
+                        // Stale resting order: never matchable, but only reaped up to the budget
+                        // so one taker can't pay an unbounded cleanup cost.
+                        if *expired_budget > 0 {
+                            indices_to_remove.push(pos);
+                            to_remove_orders.push(maker.id);
+                            expired_cancellations.push(maker.id);
+                            *expired_budget -= 1;
+                            events.push(EventKind::Out {
+                                order_id: maker.id,
+                                reason: OutReason::Expired,
+                            });
+                        }
                         continue;
                     }
+                }
+                if incoming.user_id == maker.user_id {
+                    match self_trade_policy {
+                        SelfTradePolicy::CancelResting => {
+                            indices_to_remove.push(pos);
+                            to_remove_orders.push(maker.id);
+                            self_trade_cancellations.push(maker.id);
+                            events.push(EventKind::Out {
+                                order_id: maker.id,
+                                reason: OutReason::Cancelled,
+                            });
+                            continue;
+                        }
+                        SelfTradePolicy::CancelTaking => {
+                            self_trade_cancellations.push(incoming.id);
+                            incoming.remaining = 0;
+                            events.push(EventKind::Out {
+                                order_id: incoming.id,
+                                reason: OutReason::Cancelled,
+                            });
+                            break;
+                        }
+                        SelfTradePolicy::CancelBoth => {
+                            indices_to_remove.push(pos);
+                            to_remove_orders.push(maker.id);
+                            self_trade_cancellations.push(maker.id);
+                            self_trade_cancellations.push(incoming.id);
+                            incoming.remaining = 0;
+                            events.push(EventKind::Out {
+                                order_id: maker.id,
+                                reason: OutReason::Cancelled,
+                            });
+                            events.push(EventKind::Out {
+                                order_id: incoming.id,
+                                reason: OutReason::Cancelled,
+                            });
+                            break;
+                        }
+                        SelfTradePolicy::DecrementAndCancel => {
+                            let dec = incoming.remaining.min(maker.remaining);
+                            incoming.remaining -= dec;
+                            maker.remaining -= dec;
+                            if maker.remaining == 0 {
+                                indices_to_remove.push(pos);
+                                to_remove_orders.push(maker.id);
+                                self_trade_cancellations.push(maker.id);
+                                events.push(EventKind::Out {
+                                    order_id: maker.id,
+                                    reason: OutReason::Cancelled,
+                                });
+                            }
+                            if incoming.remaining == 0 {
+                                self_trade_cancellations.push(incoming.id);
+                                events.push(EventKind::Out {
+                                    order_id: incoming.id,
+                                    reason: OutReason::Cancelled,
+                                });
+                            }
+                            continue;
+                        }
+                    }
+                }
 
-                    let fill_qty = incoming.remaining.min(maker.remaining);
-                    incoming.remaining -= fill_qty;
-                    maker.remaining -= fill_qty;
+                let fill_qty = incoming.remaining.min(maker.remaining);
+                incoming.remaining -= fill_qty;
+                maker.remaining -= fill_qty;
 
-                    fills.push(Fill {
-                        maker_id: maker.id,
-                        taker_id: incoming.id,
-                        price: book_price,
-                        qty: fill_qty,
-                    });
+                fills.push(Fill {
+                    maker_id: maker.id,
+                    taker_id: incoming.id,
+                    price: book_price,
+                    qty: fill_qty,
+                });
+                events.push(EventKind::Fill {
+                    maker_id: maker.id,
+                    taker_id: incoming.id,
+                    price: book_price,
+                    qty: fill_qty,
+                    ts: now_ts,
+                });
 
-                    if maker.remaining == 0 {
-                        indices_to_remove.push(pos);
-                        to_remove_orders.push(maker.id);
-                    }
+                if maker.remaining == 0 {
+                    indices_to_remove.push(pos);
+                    to_remove_orders.push(maker.id);
+                    events.push(EventKind::Out {
+                        order_id: maker.id,
+                        reason: OutReason::Filled,
+                    });
                 }
+            }
 
-                for &pos in indices_to_remove.iter().rev() {
-                    queue.remove(pos);
-                }
+            for &pos in indices_to_remove.iter().rev() {
+                queue.remove(pos);
+            }
 
-                if queue.is_empty() {
-                    prices_to_clean.push(book_price);
-                }
+            if queue.is_empty() {
+                levels_to_clean.push(src);
             }
         }
 
@@ -315,16 +995,25 @@ impl OrderBook {
         }
 
         // 统一清理空的价格层
-        for price in prices_to_clean {
-            if matches!(incoming.side, Side::Bid) {
-                self.asks.remove(&price);
-            } else {
-                self.bids.remove(&price);
+        for src in levels_to_clean {
+            match (incoming.side, src) {
+                (Side::Bid, LevelSrc::Fixed(p)) => {
+                    self.asks.remove(&p);
+                }
+                (Side::Bid, LevelSrc::Pegged(o)) => {
+                    self.ask_pegged.remove(&o);
+                }
+                (Side::Ask, LevelSrc::Fixed(p)) => {
+                    self.bids.remove(&p);
+                }
+                (Side::Ask, LevelSrc::Pegged(o)) => {
+                    self.bid_pegged.remove(&o);
+                }
             }
         }
     }
 
-    fn add_to_book(&mut self, order: &Order) {
+    fn add_to_book(&mut self, order: &Order, events: &mut EventQueue) {
         let entry = self.orders.vacant_entry();
         let idx = entry.key();
         entry.insert(order.clone());
@@ -339,6 +1028,25 @@ impl OrderBook {
             .entry(order.price)
             .or_insert_with(VecDeque::new)
             .push_back(idx);
+        events.push(EventKind::PlacedResting { order_id: order.id });
+    }
+
+    fn add_pegged_to_book(&mut self, order: &Order, events: &mut EventQueue) {
+        let entry = self.orders.vacant_entry();
+        let idx = entry.key();
+        entry.insert(order.clone());
+        self.by_id.insert(order.id, idx);
+
+        let target = match order.side {
+            Side::Bid => &mut self.bid_pegged,
+            Side::Ask => &mut self.ask_pegged,
+        };
+
+        target
+            .entry(order.peg_offset)
+            .or_insert_with(VecDeque::new)
+            .push_back(idx);
+        events.push(EventKind::PlacedResting { order_id: order.id });
     }
 
     fn remove_order(&mut self, id: u64) {
@@ -347,20 +1055,37 @@ impl OrderBook {
         }
     }
 
-    pub fn cancel(&mut self, id: u64) -> Option<Order> {
+    /// Remove a resting order from its book/queue without emitting any event, so callers can
+    /// attach whichever `OutReason` actually applies.
+    fn remove_resting(&mut self, id: u64) -> Option<Order> {
         if let Some(&idx) = self.by_id.get(&id) {
             let order = self.orders[idx].clone();
-            let target = match order.side {
-                Side::Bid => &mut self.bids,
-                Side::Ask => &mut self.asks,
-            };
 
-            if let Some(queue) = target.get_mut(&order.price) {
-                if let Some(pos) = queue.iter().position(|&i| i == idx) {
-                    queue.remove(pos);
+            if matches!(order.otype, OrderType::OraclePegged) {
+                let target = match order.side {
+                    Side::Bid => &mut self.bid_pegged,
+                    Side::Ask => &mut self.ask_pegged,
+                };
+                if let Some(queue) = target.get_mut(&order.peg_offset) {
+                    if let Some(pos) = queue.iter().position(|&i| i == idx) {
+                        queue.remove(pos);
+                    }
+                    if queue.is_empty() {
+                        target.remove(&order.peg_offset);
+                    }
                 }
-                if queue.is_empty() {
-                    target.remove(&order.price);
+            } else {
+                let target = match order.side {
+                    Side::Bid => &mut self.bids,
+                    Side::Ask => &mut self.asks,
+                };
+                if let Some(queue) = target.get_mut(&order.price) {
+                    if let Some(pos) = queue.iter().position(|&i| i == idx) {
+                        queue.remove(pos);
+                    }
+                    if queue.is_empty() {
+                        target.remove(&order.price);
+                    }
                 }
             }
 
@@ -371,8 +1096,33 @@ impl OrderBook {
         }
     }
 
-    pub fn modify(&mut self, id: u64, new_price: Option<u64>, new_qty: Option<u64>) {
-        if let Some(mut order) = self.cancel(id) {
+    pub fn cancel(&mut self, id: u64, events: &mut EventQueue) -> Option<Order> {
+        let order = self.remove_resting(id);
+        if order.is_some() {
+            events.push(EventKind::Out {
+                order_id: id,
+                reason: OutReason::Cancelled,
+            });
+        }
+        order
+    }
+
+    pub fn modify(
+        &mut self,
+        id: u64,
+        new_price: Option<u64>,
+        new_qty: Option<u64>,
+        events: &mut EventQueue,
+    ) -> Result<(), RiskError> {
+        if new_price.is_some() {
+            if let Some(&idx) = self.by_id.get(&id) {
+                if matches!(self.orders[idx].otype, OrderType::OraclePegged) {
+                    return Err(RiskError::PegPriceNotModifiable);
+                }
+            }
+        }
+
+        if let Some(mut order) = self.cancel(id, events) {
             if let Some(p) = new_price {
                 order.price = p;
             }
@@ -380,39 +1130,110 @@ impl OrderBook {
                 order.qty = q;
                 order.remaining = q;
             }
-            self.add_to_book(&order);
+            if matches!(order.otype, OrderType::OraclePegged) {
+                self.add_pegged_to_book(&order, events);
+            } else {
+                self.add_to_book(&order, events);
+            }
         }
+        Ok(())
+    }
+
+    /// Background pruning of expired resting orders (across both fixed and pegged levels), for
+    /// callers that don't want to wait for a taker to lazily trip over them. Cancels at most `max`.
+    pub fn reap_expired(&mut self, now_ts: u64, max: usize, events: &mut EventQueue) -> Vec<u64> {
+        let mut expired_ids = Vec::new();
+        'outer: for queue in self
+            .bids
+            .values()
+            .chain(self.asks.values())
+            .chain(self.bid_pegged.values())
+            .chain(self.ask_pegged.values())
+        {
+            for &idx in queue.iter() {
+                if expired_ids.len() >= max {
+                    break 'outer;
+                }
+                if let Some(expire_ts) = self.orders[idx].expire_ts {
+                    if expire_ts <= now_ts {
+                        expired_ids.push(self.orders[idx].id);
+                    }
+                }
+            }
+        }
+
+        for &id in &expired_ids {
+            self.remove_resting(id);
+            events.push(EventKind::Out {
+                order_id: id,
+                reason: OutReason::Expired,
+            });
+        }
+        expired_ids
     }
 
     pub fn batch_submit(
         &mut self,
-        orders: Vec<(u64, u64, OrderType, Side, u64, u64)>,
+        requests: Vec<OrderRequest>,
+        now_ts: u64,
         risk: &mut RiskEngine,
-    ) -> Vec<Result<Vec<Fill>, RiskError>> {
-        orders
+        events: &mut EventQueue,
+    ) -> Vec<Result<MatchReport, RiskError>> {
+        requests
             .into_iter()
-            .map(|(id, uid, ot, side, p, q)| self.submit(id, uid, ot, side, p, q, risk))
+            .map(|req| self.submit(req, now_ts, risk, events))
             .collect()
     }
 
-    pub fn get_l2_snapshot(&self, depth: usize) -> (Vec<(u64, u64)>, Vec<(u64, u64)>) {
-        let mut bids = Vec::with_capacity(depth.min(self.bids.len()));
-        let mut bid_iter = self.bids.iter().rev();
-        for _ in 0..depth {
-            if let Some((&price, queue)) = bid_iter.next() {
-                let total: u64 = queue.iter().map(|&idx| self.orders[idx].remaining).sum();
-                bids.push((price, total));
+    /// Total resting (non-expired) quantity at a merged fixed/pegged level, on `side`'s own book.
+    fn resting_level_qty(&self, side: Side, src: LevelSrc, now_ts: u64) -> u64 {
+        let queue = match (side, src) {
+            (Side::Bid, LevelSrc::Fixed(p)) => self.bids.get(&p),
+            (Side::Bid, LevelSrc::Pegged(o)) => self.bid_pegged.get(&o),
+            (Side::Ask, LevelSrc::Fixed(p)) => self.asks.get(&p),
+            (Side::Ask, LevelSrc::Pegged(o)) => self.ask_pegged.get(&o),
+        };
+        queue.map_or(0, |q| {
+            q.iter()
+                .filter(|&&idx| {
+                    self.orders[idx]
+                        .expire_ts
+                        .map_or(true, |expire_ts| expire_ts > now_ts)
+                })
+                .map(|&idx| self.orders[idx].remaining)
+                .sum()
+        })
+    }
+
+    /// Merge fixed and oracle-pegged resting levels (pegged ones at their current effective
+    /// price) into up to `depth` price levels, combining a pegged level that lands on the same
+    /// effective price as a fixed level.
+    pub fn get_l2_snapshot(&self, depth: usize, now_ts: u64) -> (Vec<(u64, u64)>, Vec<(u64, u64)>) {
+        let mut bids: Vec<(u64, u64)> = Vec::new();
+        for (price, src) in self.bid_levels_in_order() {
+            let qty = self.resting_level_qty(Side::Bid, src, now_ts);
+            if qty == 0 {
+                continue;
+            }
+            if bids.last().map_or(false, |&(p, _)| p == price) {
+                bids.last_mut().unwrap().1 += qty;
+            } else if bids.len() < depth {
+                bids.push((price, qty));
             } else {
                 break;
             }
         }
 
-        let mut asks = Vec::with_capacity(depth.min(self.asks.len()));
-        let mut ask_iter = self.asks.iter();
-        for _ in 0..depth {
-            if let Some((&price, queue)) = ask_iter.next() {
-                let total: u64 = queue.iter().map(|&idx| self.orders[idx].remaining).sum();
-                asks.push((price, total));
+        let mut asks: Vec<(u64, u64)> = Vec::new();
+        for (price, src) in self.ask_levels_in_order() {
+            let qty = self.resting_level_qty(Side::Ask, src, now_ts);
+            if qty == 0 {
+                continue;
+            }
+            if asks.last().map_or(false, |&(p, _)| p == price) {
+                asks.last_mut().unwrap().1 += qty;
+            } else if asks.len() < depth {
+                asks.push((price, qty));
             } else {
                 break;
             }
@@ -425,6 +1246,7 @@ impl OrderBook {
 pub struct MatchingEngine {
     books: HashMap<String, OrderBook>,
     risk: RiskEngine,
+    events: EventQueue,
 }
 
 impl MatchingEngine {
@@ -432,9 +1254,16 @@ impl MatchingEngine {
         MatchingEngine {
             books: HashMap::new(),
             risk: RiskEngine::new(),
+            events: EventQueue::new(),
         }
     }
 
+    /// Remove and return up to `max` pending matching events, oldest first, for a settlement
+    /// process to consume idempotently (each event carries a monotonically increasing `seq`).
+    pub fn drain_events(&mut self, max: usize) -> Vec<Event> {
+        self.events.drain(max)
+    }
+
     pub fn set_rate_limit(&mut self, user_id: u64, limit: u64) {
         self.risk.rate_limits.insert(user_id, limit);
     }
@@ -442,51 +1271,451 @@ impl MatchingEngine {
     pub fn submit(
         &mut self,
         symbol: &str,
-        id: u64,
-        user_id: u64,
-        otype: OrderType,
-        side: Side,
-        price: u64,
-        qty: u64,
-    ) -> Result<Vec<Fill>, RiskError> {
+        req: OrderRequest,
+        now_ts: u64,
+    ) -> Result<MatchReport, RiskError> {
         let book = self
             .books
             .entry(symbol.to_string())
             .or_insert_with(OrderBook::new);
-        book.submit(id, user_id, otype, side, price, qty, &mut self.risk)
+        book.submit(req, now_ts, &mut self.risk, &mut self.events)
+    }
+
+    /// Background pruning of expired resting orders for `symbol`.
+    pub fn reap_expired(&mut self, symbol: &str, now_ts: u64, max: usize) -> Vec<u64> {
+        let events = &mut self.events;
+        self.books
+            .get_mut(symbol)
+            .map_or(vec![], |book| book.reap_expired(now_ts, max, events))
+    }
+
+    /// Configure `symbol`'s tick size, lot size, and minimum order size.
+    pub fn configure_market(&mut self, symbol: &str, config: MarketConfig) -> Result<(), RiskError> {
+        self.books
+            .entry(symbol.to_string())
+            .or_insert_with(OrderBook::new)
+            .configure_market(config)
+    }
+
+    /// Set the current oracle price for `symbol`, repricing (and potentially matching) any
+    /// resting `OraclePegged` orders that now cross the book.
+    pub fn set_oracle_price(&mut self, symbol: &str, price: u64, now_ts: u64) -> MatchReport {
+        let book = self
+            .books
+            .entry(symbol.to_string())
+            .or_insert_with(OrderBook::new);
+        book.set_oracle_price(price, now_ts, &self.risk, &mut self.events)
     }
 
     pub fn cancel(&mut self, symbol: &str, id: u64) -> Option<Order> {
-        self.books.get_mut(symbol).and_then(|book| book.cancel(id))
+        let events = &mut self.events;
+        self.books.get_mut(symbol).and_then(|book| book.cancel(id, events))
     }
 
-    pub fn modify(&mut self, symbol: &str, id: u64, new_price: Option<u64>, new_qty: Option<u64>) {
-        if let Some(book) = self.books.get_mut(symbol) {
-            book.modify(id, new_price, new_qty);
+    pub fn modify(
+        &mut self,
+        symbol: &str,
+        id: u64,
+        new_price: Option<u64>,
+        new_qty: Option<u64>,
+    ) -> Result<(), RiskError> {
+        let events = &mut self.events;
+        match self.books.get_mut(symbol) {
+            Some(book) => book.modify(id, new_price, new_qty, events),
+            None => Ok(()),
         }
     }
 
     pub fn batch_submit(
         &mut self,
         symbol: &str,
-        orders: Vec<(u64, u64, OrderType, Side, u64, u64)>,
-    ) -> Vec<Result<Vec<Fill>, RiskError>> {
+        requests: Vec<OrderRequest>,
+        now_ts: u64,
+    ) -> Vec<Result<MatchReport, RiskError>> {
+        let risk = &mut self.risk;
+        let events = &mut self.events;
         self.books
             .get_mut(symbol)
-            .map_or(vec![], |book| book.batch_submit(orders, &mut self.risk))
+            .map_or(vec![], |book| book.batch_submit(requests, now_ts, risk, events))
+    }
+
+    pub fn set_self_trade_policy(&mut self, user_id: u64, policy: SelfTradePolicy) {
+        self.risk.set_self_trade_policy(user_id, policy);
     }
 
     pub fn get_l2_snapshot(
         &self,
         symbol: &str,
         depth: usize,
+        now_ts: u64,
     ) -> Option<(Vec<(u64, u64)>, Vec<(u64, u64)>)> {
         self.books
             .get(symbol)
-            .map(|book| book.get_l2_snapshot(depth))
+            .map(|book| book.get_l2_snapshot(depth, now_ts))
     }
 
     pub fn set_position_limit(&mut self, user_id: u64, max_qty: u64) {
         self.risk.set_position_limit(user_id, max_qty);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Submit a plain fixed-price order with no peg offset, expiry, or slippage bound.
+    fn submit_simple(
+        book: &mut OrderBook,
+        risk: &mut RiskEngine,
+        events: &mut EventQueue,
+        id: u64,
+        user_id: u64,
+        otype: OrderType,
+        side: Side,
+        price: u64,
+        qty: u64,
+    ) -> MatchReport {
+        book.submit(
+            OrderRequest {
+                id,
+                user_id,
+                otype,
+                side,
+                price,
+                qty,
+                ..Default::default()
+            },
+            0,
+            risk,
+            events,
+        )
+        .expect("submit should succeed")
+    }
+
+    #[test]
+    fn self_trade_cancel_resting_is_the_default_policy() {
+        let mut book = OrderBook::new();
+        let mut risk = RiskEngine::new();
+        let mut events = EventQueue::new();
+
+        submit_simple(
+            &mut book, &mut risk, &mut events, 1, 42, OrderType::Limit, Side::Bid,
+            100 * PRECISION, PRECISION,
+        );
+        let report = submit_simple(
+            &mut book, &mut risk, &mut events, 2, 42, OrderType::Limit, Side::Ask,
+            100 * PRECISION, PRECISION,
+        );
+
+        assert!(report.fills.is_empty());
+        assert_eq!(report.self_trade_cancellations, vec![1]);
+        let (bids, asks) = book.get_l2_snapshot(10, 0);
+        assert!(bids.is_empty());
+        assert_eq!(asks, vec![(100 * PRECISION, PRECISION)]);
+    }
+
+    #[test]
+    fn post_only_is_rejected_against_a_resting_pegged_order() {
+        let mut book = OrderBook::new();
+        let mut risk = RiskEngine::new();
+        let mut events = EventQueue::new();
+
+        book.set_oracle_price(100 * PRECISION, 0, &risk, &mut events);
+        book.submit(
+            OrderRequest { id: 1, user_id: 1, otype: OrderType::OraclePegged, side: Side::Ask, price: 0, qty: PRECISION, ..Default::default() },
+            0, &mut risk, &mut events,
+        )
+        .unwrap();
+
+        let report = book
+            .submit(
+                OrderRequest { id: 2, user_id: 2, otype: OrderType::PostOnly, side: Side::Bid, price: 150 * PRECISION, qty: PRECISION, ..Default::default() },
+                0, &mut risk, &mut events,
+            )
+            .unwrap();
+
+        assert!(report.fills.is_empty());
+        assert!(report.resting_price.is_none());
+        let (bids, _) = book.get_l2_snapshot(10, 0);
+        assert!(bids.is_empty());
+    }
+
+    #[test]
+    fn l2_snapshot_includes_pegged_levels() {
+        let mut book = OrderBook::new();
+        let mut risk = RiskEngine::new();
+        let mut events = EventQueue::new();
+
+        book.set_oracle_price(100 * PRECISION, 0, &risk, &mut events);
+        book.submit(
+            OrderRequest { id: 1, user_id: 1, otype: OrderType::OraclePegged, side: Side::Ask, price: 0, qty: PRECISION, ..Default::default() },
+            0, &mut risk, &mut events,
+        )
+        .unwrap();
+
+        let (_, asks) = book.get_l2_snapshot(10, 0);
+        assert_eq!(asks, vec![(100 * PRECISION, PRECISION)]);
+    }
+
+    #[test]
+    fn expired_gtt_maker_is_skipped_when_oracle_reprice_crosses_it() {
+        let mut book = OrderBook::new();
+        let mut risk = RiskEngine::new();
+        let mut events = EventQueue::new();
+
+        book.set_oracle_price(50 * PRECISION, 0, &risk, &mut events);
+        // Resting pegged bid, well below the GTT ask below so neither crosses yet.
+        book.submit(
+            OrderRequest { id: 1, user_id: 1, otype: OrderType::OraclePegged, side: Side::Bid, price: 0, qty: PRECISION, ..Default::default() },
+            0, &mut risk, &mut events,
+        )
+        .unwrap();
+        // Resting GTT ask that will have expired (in wall-clock terms) by the time the oracle moves.
+        book.submit(
+            OrderRequest {
+                id: 2, user_id: 2, otype: OrderType::GoodTillTime, side: Side::Ask,
+                price: 60 * PRECISION, qty: PRECISION, expire_ts: Some(100), ..Default::default()
+            },
+            0, &mut risk, &mut events,
+        )
+        .unwrap();
+
+        // The oracle moves the pegged bid's effective price above the resting ask, at a time past
+        // the ask's expiry; the ask must be skipped rather than matched.
+        let report = book.set_oracle_price(100 * PRECISION, 200, &risk, &mut events);
+
+        assert!(report.fills.is_empty());
+    }
+
+    #[test]
+    fn configure_market_rejects_zero_tick_size() {
+        let mut book = OrderBook::new();
+        let mut config = MarketConfig::default();
+        config.tick_size = 0;
+
+        assert!(matches!(
+            book.configure_market(config),
+            Err(RiskError::InvalidMarketConfig)
+        ));
+    }
+
+    #[test]
+    fn post_only_slide_rejected_when_clamp_would_still_cross() {
+        // The best ask already sits at the price floor, so there's no room to slide the bid
+        // strictly inside it; clamping into [price_floor, price_ceil] would land the bid back on
+        // the ask instead of passive. It must be rejected rather than rest crossed.
+        let mut book = OrderBook::new();
+        let mut risk = RiskEngine::new();
+        let mut events = EventQueue::new();
+
+        submit_simple(
+            &mut book, &mut risk, &mut events, 1, 1, OrderType::Limit, Side::Ask,
+            MIN_PRICE, PRECISION,
+        );
+        let report = submit_simple(
+            &mut book, &mut risk, &mut events, 2, 2, OrderType::PostOnlySlide, Side::Bid,
+            MIN_PRICE, PRECISION,
+        );
+
+        assert!(report.resting_price.is_none());
+        let (bids, asks) = book.get_l2_snapshot(10, 0);
+        assert!(bids.is_empty());
+        assert_eq!(asks, vec![(MIN_PRICE, PRECISION)]);
+    }
+
+    #[test]
+    fn fok_ignores_expired_maker_as_phantom_liquidity() {
+        let mut book = OrderBook::new();
+        let mut risk = RiskEngine::new();
+        let mut events = EventQueue::new();
+
+        // Live ask for 5 at 100, plus an already-expired ask for 5 at 101.
+        submit_simple(&mut book, &mut risk, &mut events, 1, 1, OrderType::Limit, Side::Ask, 100 * PRECISION, 5 * PRECISION);
+        book.submit(
+            OrderRequest {
+                id: 2, user_id: 2, otype: OrderType::GoodTillTime, side: Side::Ask,
+                price: 101 * PRECISION, qty: 5 * PRECISION, expire_ts: Some(10), ..Default::default()
+            },
+            0, &mut risk, &mut events,
+        )
+        .unwrap();
+
+        // A 10-unit FOK bid can only really be filled by the live 5; it must not match at all.
+        let report = book
+            .submit(
+                OrderRequest { id: 3, user_id: 3, otype: OrderType::FOK, side: Side::Bid, price: 101 * PRECISION, qty: 10 * PRECISION, ..Default::default() },
+                20, &mut risk, &mut events,
+            )
+            .unwrap();
+
+        assert!(report.fills.is_empty());
+    }
+
+    #[test]
+    fn post_only_rests_when_only_crossing_liquidity_is_expired() {
+        let mut book = OrderBook::new();
+        let mut risk = RiskEngine::new();
+        let mut events = EventQueue::new();
+
+        book.submit(
+            OrderRequest {
+                id: 1, user_id: 1, otype: OrderType::GoodTillTime, side: Side::Ask,
+                price: 100 * PRECISION, qty: PRECISION, expire_ts: Some(10), ..Default::default()
+            },
+            0, &mut risk, &mut events,
+        )
+        .unwrap();
+
+        let report = book
+            .submit(
+                OrderRequest { id: 2, user_id: 2, otype: OrderType::PostOnly, side: Side::Bid, price: 100 * PRECISION, qty: PRECISION, ..Default::default() },
+                20, &mut risk, &mut events,
+            )
+            .unwrap();
+
+        assert_eq!(report.resting_price, Some(100 * PRECISION));
+    }
+
+    #[test]
+    fn modify_rejects_new_price_on_a_pegged_order() {
+        let mut book = OrderBook::new();
+        let mut risk = RiskEngine::new();
+        let mut events = EventQueue::new();
+
+        book.set_oracle_price(100 * PRECISION, 0, &risk, &mut events);
+        book.submit(
+            OrderRequest {
+                id: 1, user_id: 1, otype: OrderType::OraclePegged, side: Side::Ask,
+                price: 0, qty: PRECISION, peg_offset: PRECISION as i64, ..Default::default()
+            },
+            0, &mut risk, &mut events,
+        )
+        .unwrap();
+
+        let result = book.modify(1, Some(200 * PRECISION), None, &mut events);
+
+        assert_eq!(result, Err(RiskError::PegPriceNotModifiable));
+        let (_, asks) = book.get_l2_snapshot(10, 0);
+        assert_eq!(asks, vec![(101 * PRECISION, PRECISION)]);
+    }
+
+    #[test]
+    fn oracle_pegged_submit_does_not_panic_on_extreme_peg_offset() {
+        let mut book = OrderBook::new();
+        let mut risk = RiskEngine::new();
+        let mut events = EventQueue::new();
+
+        book.set_oracle_price(100 * PRECISION, 0, &risk, &mut events);
+        let report = book
+            .submit(
+                OrderRequest {
+                    id: 1, user_id: 1, otype: OrderType::OraclePegged, side: Side::Bid,
+                    price: 0, qty: PRECISION, peg_offset: i64::MAX, ..Default::default()
+                },
+                0, &mut risk, &mut events,
+            )
+            .unwrap();
+
+        assert_eq!(report.resting_price, Some(MAX_PRICE));
+    }
+
+    #[test]
+    fn market_slippage_limit_ignores_expired_phantom_touch() {
+        let mut book = OrderBook::new();
+        let mut risk = RiskEngine::new();
+        let mut events = EventQueue::new();
+
+        // An already-expired ask sits ahead of the true touch; the slippage limit must be
+        // derived from the live 101 ask, not the phantom expired 100 ask.
+        book.submit(
+            OrderRequest {
+                id: 1, user_id: 1, otype: OrderType::GoodTillTime, side: Side::Ask,
+                price: 100 * PRECISION, qty: PRECISION, expire_ts: Some(10), ..Default::default()
+            },
+            0, &mut risk, &mut events,
+        )
+        .unwrap();
+        submit_simple(
+            &mut book, &mut risk, &mut events, 2, 2, OrderType::Limit, Side::Ask,
+            101 * PRECISION, PRECISION,
+        );
+
+        let report = book
+            .submit(
+                OrderRequest {
+                    id: 3, user_id: 3, otype: OrderType::Market, side: Side::Bid,
+                    price: 0, qty: PRECISION, max_slippage_bps: Some(100), ..Default::default()
+                },
+                20, &mut risk, &mut events,
+            )
+            .unwrap();
+
+        assert_eq!(report.fills.len(), 1);
+        assert_eq!(report.fills[0].price, 101 * PRECISION);
+    }
+
+    #[test]
+    fn oracle_reprice_crosses_two_pegged_orders_with_no_fixed_orders_resting() {
+        // Both sides rest purely on pegged offsets; the crossing check in `try_cross_one_pegged`
+        // must consult merged (fixed + pegged) levels, not just `self.asks`/`self.bids`, or an
+        // oracle move that only brings pegged quotes together is never matched.
+        let mut book = OrderBook::new();
+        let mut risk = RiskEngine::new();
+        let mut events = EventQueue::new();
+
+        book.set_oracle_price(10 * PRECISION, 0, &risk, &mut events);
+        book.submit(
+            OrderRequest {
+                id: 1, user_id: 1, otype: OrderType::OraclePegged, side: Side::Bid,
+                price: 0, qty: PRECISION, peg_offset: -5 * PRECISION as i64, ..Default::default()
+            },
+            0, &mut risk, &mut events,
+        )
+        .unwrap();
+        book.submit(
+            OrderRequest {
+                id: 2, user_id: 2, otype: OrderType::OraclePegged, side: Side::Ask,
+                price: 0, qty: PRECISION, peg_offset: -3 * PRECISION as i64, ..Default::default()
+            },
+            0, &mut risk, &mut events,
+        )
+        .unwrap();
+        // Not crossed yet: bid rests at 5 * PRECISION, ask at 7 * PRECISION.
+        let (bids, asks) = book.get_l2_snapshot(10, 0);
+        assert_eq!(bids, vec![(5 * PRECISION, PRECISION)]);
+        assert_eq!(asks, vec![(7 * PRECISION, PRECISION)]);
+
+        // Dropping the oracle to the floor saturates both offsets to the same effective price.
+        let report = book.set_oracle_price(MIN_PRICE, 0, &risk, &mut events);
+
+        assert_eq!(report.fills.len(), 1);
+        let (bids, asks) = book.get_l2_snapshot(10, 0);
+        assert!(bids.is_empty());
+        assert!(asks.is_empty());
+    }
+
+    #[test]
+    fn market_submit_does_not_panic_on_extreme_max_slippage_bps() {
+        let mut book = OrderBook::new();
+        let mut risk = RiskEngine::new();
+        let mut events = EventQueue::new();
+
+        submit_simple(
+            &mut book, &mut risk, &mut events, 1, 1, OrderType::Limit, Side::Ask,
+            100 * PRECISION, PRECISION,
+        );
+
+        let report = book
+            .submit(
+                OrderRequest {
+                    id: 2, user_id: 2, otype: OrderType::Market, side: Side::Bid,
+                    price: 100 * PRECISION, qty: PRECISION, max_slippage_bps: Some(u64::MAX),
+                    ..Default::default()
+                },
+                0, &mut risk, &mut events,
+            )
+            .unwrap();
+
+        assert_eq!(report.fills.len(), 1);
+    }
+}
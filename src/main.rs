@@ -1,4 +1,4 @@
-use matching_engine::{MatchingEngine, OrderType, PRECISION, Side};
+use matching_engine::{MatchingEngine, OrderRequest, OrderType, PRECISION, Side};
 use rand::prelude::*;
 use std::time::Instant;
 
@@ -38,12 +38,16 @@ fn main() {
         };
         let _ = engine.submit(
             "BTCUSDT",
-            i as u64,
-            1,
-            OrderType::Limit,
-            if i % 2 == 0 { Side::Bid } else { Side::Ask },
-            price,
-            10 * PRECISION,
+            OrderRequest {
+                id: i as u64,
+                user_id: 1,
+                otype: OrderType::Limit,
+                side: if i % 2 == 0 { Side::Bid } else { Side::Ask },
+                price,
+                qty: 10 * PRECISION,
+                ..Default::default()
+            },
+            0,
         );
     }
 
@@ -91,7 +95,19 @@ where
         let (otype, side, _, qty) = order_factory(i, base_price);
 
         let t0 = Instant::now();
-        let result = engine.submit("BTCUSDT", order_id, 1, otype, side, price, qty);
+        let result = engine.submit(
+            "BTCUSDT",
+            OrderRequest {
+                id: order_id,
+                user_id: 1,
+                otype,
+                side,
+                price,
+                qty,
+                ..Default::default()
+            },
+            0,
+        );
         let duration = t0.elapsed();
 
         times.push(duration);